@@ -1,17 +1,40 @@
 use std::env::set_current_dir;
-use std::fs::OpenOptions;
-use std::os::fd::{IntoRawFd, RawFd};
+use std::ffi::CString;
+use std::os::fd::RawFd;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
 use std::process;
 
+mod pidfile;
+mod privilege;
+mod redirect;
+mod supervise;
+
+pub use privilege::{Group, User};
+pub use redirect::Redirect;
+pub use supervise::{RestartPolicy, SuperviseOptions};
+
 #[repr(i32)]
 #[derive(Clone, Copy)]
-enum ExitCodes {
+pub(crate) enum ExitCodes {
     Ok = 0,
     ChildFailedToFork,
     ChildSetsidFailed,
     GrandchildChdirFailed,
-    GrandchildOpenDevNullFailed,
+    GrandchildOpenStdinFailed,
+    GrandchildOpenStdoutFailed,
+    GrandchildOpenStderrFailed,
     GrandchildFailedTooSoon,
+    GrandchildResolveGroupFailed,
+    GrandchildSetgroupsFailed,
+    GrandchildSetgidFailed,
+    GrandchildResolveUserFailed,
+    GrandchildInitgroupsFailed,
+    GrandchildSetuidFailed,
+    GrandchildPidFileFailed,
+    GrandchildAlreadyRunning,
+    GrandchildSupervisorForkFailed,
+    GrandchildChrootFailed,
 }
 
 impl From<ExitCodes> for i32 {
@@ -29,8 +52,20 @@ impl TryFrom<i32> for ExitCodes {
             1 => Ok(ExitCodes::ChildFailedToFork),
             2 => Ok(ExitCodes::ChildSetsidFailed),
             3 => Ok(ExitCodes::GrandchildChdirFailed),
-            4 => Ok(ExitCodes::GrandchildOpenDevNullFailed),
-            5 => Ok(ExitCodes::GrandchildFailedTooSoon),
+            4 => Ok(ExitCodes::GrandchildOpenStdinFailed),
+            5 => Ok(ExitCodes::GrandchildOpenStdoutFailed),
+            6 => Ok(ExitCodes::GrandchildOpenStderrFailed),
+            7 => Ok(ExitCodes::GrandchildFailedTooSoon),
+            8 => Ok(ExitCodes::GrandchildResolveGroupFailed),
+            9 => Ok(ExitCodes::GrandchildSetgroupsFailed),
+            10 => Ok(ExitCodes::GrandchildSetgidFailed),
+            11 => Ok(ExitCodes::GrandchildResolveUserFailed),
+            12 => Ok(ExitCodes::GrandchildInitgroupsFailed),
+            13 => Ok(ExitCodes::GrandchildSetuidFailed),
+            14 => Ok(ExitCodes::GrandchildPidFileFailed),
+            15 => Ok(ExitCodes::GrandchildAlreadyRunning),
+            16 => Ok(ExitCodes::GrandchildSupervisorForkFailed),
+            17 => Ok(ExitCodes::GrandchildChrootFailed),
             _ => Err("Unknown exitcode"),
         }
     }
@@ -39,10 +74,14 @@ impl TryFrom<i32> for ExitCodes {
 #[derive(Debug)]
 pub enum Identity {
     Original,
-    Daemon,
+    Daemon {
+        /// The umask inherited from the parent, as it was before
+        /// [`DaemonizeOptions::umask`] was applied.
+        previous_umask: libc::mode_t,
+    },
 }
 
-enum Fork {
+pub(crate) enum Fork {
     Parent { child: i32 },
     Child,
 }
@@ -87,12 +126,48 @@ fn wait_for_success(pid: i32) -> Result<(), std::io::Error> {
         Ok(ExitCodes::GrandchildChdirFailed) => {
             Err(std::io::Error::other("GrandChild chdir failed"))
         },
-        Ok(ExitCodes::GrandchildOpenDevNullFailed) => {
-            Err(std::io::Error::other("GrandChild open /dev/null failed"))
+        Ok(ExitCodes::GrandchildOpenStdinFailed) => {
+            Err(std::io::Error::other("GrandChild failed to open stdin target"))
+        },
+        Ok(ExitCodes::GrandchildOpenStdoutFailed) => {
+            Err(std::io::Error::other("GrandChild failed to open stdout target"))
+        },
+        Ok(ExitCodes::GrandchildOpenStderrFailed) => {
+            Err(std::io::Error::other("GrandChild failed to open stderr target"))
         },
         Ok(ExitCodes::GrandchildFailedTooSoon) => {
             Err(std::io::Error::other("GrandChild failed too soon"))
         },
+        Ok(ExitCodes::GrandchildResolveGroupFailed) => {
+            Err(std::io::Error::other("GrandChild failed to resolve group"))
+        },
+        Ok(ExitCodes::GrandchildSetgroupsFailed) => {
+            Err(std::io::Error::other("GrandChild setgroups failed"))
+        },
+        Ok(ExitCodes::GrandchildSetgidFailed) => {
+            Err(std::io::Error::other("GrandChild setgid failed"))
+        },
+        Ok(ExitCodes::GrandchildResolveUserFailed) => {
+            Err(std::io::Error::other("GrandChild failed to resolve user"))
+        },
+        Ok(ExitCodes::GrandchildInitgroupsFailed) => {
+            Err(std::io::Error::other("GrandChild initgroups failed"))
+        },
+        Ok(ExitCodes::GrandchildSetuidFailed) => {
+            Err(std::io::Error::other("GrandChild setuid failed"))
+        },
+        Ok(ExitCodes::GrandchildPidFileFailed) => {
+            Err(std::io::Error::other("GrandChild failed to create/lock pid file"))
+        },
+        Ok(ExitCodes::GrandchildAlreadyRunning) => {
+            Err(std::io::Error::other("Another instance is already running"))
+        },
+        Ok(ExitCodes::GrandchildSupervisorForkFailed) => {
+            Err(std::io::Error::other("Supervisor failed to fork worker"))
+        },
+        Ok(ExitCodes::GrandchildChrootFailed) => {
+            Err(std::io::Error::other("GrandChild chroot failed"))
+        },
         Err(err) => Err(std::io::Error::other(format!(
             "Unspecified error code: {}",
             err
@@ -112,7 +187,7 @@ fn dup2(from: RawFd, to: RawFd) -> Result<(), std::io::Error> {
     cvt::cvt_r(|| unsafe { libc::dup2(from, to) }).map(|_| ())
 }
 
-fn fork() -> Result<Fork, std::io::Error> {
+pub(crate) fn fork() -> Result<Fork, std::io::Error> {
     // we're not capturing `EAGAIN` here, as the errors
     // described there aren't resolvable by themselves
     let pid = unsafe { libc::fork() };
@@ -132,6 +207,12 @@ fn setsid() -> Result<(), std::io::Error> {
     cvt::cvt(sid).map(|_| ())
 }
 
+fn chroot(path: &Path) -> Result<(), std::io::Error> {
+    let path = CString::new(path.as_os_str().as_bytes()).map_err(std::io::Error::other)?;
+
+    cvt::cvt(unsafe { libc::chroot(path.as_ptr()) }).map(|_| ())
+}
+
 /// Daemonizes the process
 ///
 /// # Errors
@@ -143,6 +224,17 @@ pub fn daemonize() -> Result<Identity, std::io::Error> {
 
 pub struct DaemonizeOptions {
     timeout_ms: Option<u16>,
+    user: Option<User>,
+    group: Option<Group>,
+    initgroups: bool,
+    pid_file: Option<PathBuf>,
+    stdin: Redirect,
+    stdout: Redirect,
+    stderr: Redirect,
+    supervise: Option<SuperviseOptions>,
+    working_directory: PathBuf,
+    chroot: Option<PathBuf>,
+    umask: libc::mode_t,
 }
 
 impl Default for DaemonizeOptions {
@@ -154,7 +246,20 @@ impl Default for DaemonizeOptions {
 impl DaemonizeOptions {
     #[must_use]
     pub fn new() -> Self {
-        Self { timeout_ms: None }
+        Self {
+            timeout_ms: None,
+            user: None,
+            group: None,
+            initgroups: false,
+            pid_file: None,
+            stdin: Redirect::Null,
+            stdout: Redirect::Null,
+            stderr: Redirect::Null,
+            supervise: None,
+            working_directory: PathBuf::from("/"),
+            chroot: None,
+            umask: 0,
+        }
     }
 
     #[must_use]
@@ -164,6 +269,120 @@ impl DaemonizeOptions {
         self
     }
 
+    /// Drops to this user (via `setuid`) once the daemon is detached.
+    ///
+    /// Applied after [`DaemonizeOptions::group`], since dropping the uid
+    /// first would strip the privilege needed to still change gid.
+    #[must_use]
+    pub fn user(mut self, user: impl Into<User>) -> Self {
+        self.user = Some(user.into());
+
+        self
+    }
+
+    /// Drops to this group (via `setgid`) once the daemon is detached.
+    #[must_use]
+    pub fn group(mut self, group: impl Into<Group>) -> Self {
+        self.group = Some(group.into());
+
+        self
+    }
+
+    /// Whether to also install the target user's supplementary groups (via
+    /// `initgroups`). Requires [`DaemonizeOptions::user`] to be set, since
+    /// `initgroups(3)` needs a login name to look up.
+    #[must_use]
+    pub fn initgroups(mut self, initgroups: bool) -> Self {
+        self.initgroups = initgroups;
+
+        self
+    }
+
+    /// Writes the grandchild's pid to `path`, guarded by a non-blocking
+    /// exclusive `flock` held for the daemon's lifetime.
+    ///
+    /// If the lock is already held, [`DaemonizeOptions::daemonize`] returns
+    /// an `Err` instead of starting a second instance.
+    #[must_use]
+    pub fn pid_file(mut self, path: impl Into<PathBuf>) -> Self {
+        self.pid_file = Some(path.into());
+
+        self
+    }
+
+    /// Where to redirect the daemon's stdin. Defaults to `/dev/null`.
+    #[must_use]
+    pub fn stdin(mut self, stdin: impl Into<Redirect>) -> Self {
+        self.stdin = stdin.into();
+
+        self
+    }
+
+    /// Where to redirect the daemon's stdout, e.g. a logfile opened for
+    /// appending. Defaults to `/dev/null`.
+    #[must_use]
+    pub fn stdout(mut self, stdout: impl Into<Redirect>) -> Self {
+        self.stdout = stdout.into();
+
+        self
+    }
+
+    /// Where to redirect the daemon's stderr, e.g. a logfile opened for
+    /// appending. Defaults to `/dev/null`.
+    #[must_use]
+    pub fn stderr(mut self, stderr: impl Into<Redirect>) -> Self {
+        self.stderr = stderr.into();
+
+        self
+    }
+
+    /// Turns the detached daemon into a self-contained supervisor: the
+    /// detached leader repeatedly forks the actual worker and respawns it
+    /// per `options`, instead of requiring an external supervisor like
+    /// systemd or runit.
+    ///
+    /// [`DaemonizeOptions::daemonize`] only returns `Identity::Daemon` in the
+    /// freshly-forked worker; the supervisor process itself never returns to
+    /// user code.
+    #[must_use]
+    pub fn supervise(mut self, options: SuperviseOptions) -> Self {
+        self.supervise = Some(options);
+
+        self
+    }
+
+    /// The directory to `chdir` into once detached. Defaults to `/`, so the
+    /// daemon doesn't keep an arbitrary directory (and the filesystem
+    /// backing it) busy.
+    #[must_use]
+    pub fn working_directory(mut self, path: impl Into<PathBuf>) -> Self {
+        self.working_directory = path.into();
+
+        self
+    }
+
+    /// Confines the daemon to `path` via `chroot`, applied while still root
+    /// and before any privilege drop or the daemon could escape the jail.
+    /// [`DaemonizeOptions::working_directory`] is resolved relative to the
+    /// new root.
+    #[must_use]
+    pub fn chroot(mut self, path: impl Into<PathBuf>) -> Self {
+        self.chroot = Some(path.into());
+
+        self
+    }
+
+    /// The `umask` applied once detached, so the daemon has complete control
+    /// over the permissions of anything it writes instead of inheriting
+    /// whatever mask the parent happened to have. Defaults to `0`; callers
+    /// that want sane defaults for created files can pass e.g. `0o027`.
+    #[must_use]
+    pub fn umask(mut self, umask: libc::mode_t) -> Self {
+        self.umask = umask;
+
+        self
+    }
+
     /// Daemonizes the process
     ///
     /// # Errors
@@ -220,17 +439,124 @@ impl DaemonizeOptions {
 
         // we're now in the grand-child
 
-        // chdir("/") to ensure that our process doesn't keep any directory in use.
-        // Failure to do this could make it so that an administrator couldn't unmount a filesystem, because it was our current directory.
-        // [Equivalently, we could change to any directory containing files important to the daemon's operation.]
-        if let Err(_err) = set_current_dir("/") {
-            // Couldn't chdir to "/", which shouldn't fail
+        // if supervision is requested, the grand-child becomes a supervisor that forks and
+        // respawns the actual worker; it never returns from here. Only the freshly-forked
+        // worker falls through to the rest of the daemonizing below.
+        if let Some(options) = &self.supervise
+            && let Err(_err) = supervise::supervise(options)
+        {
+            process::exit(ExitCodes::GrandchildSupervisorForkFailed.into());
+        }
+
+        // chroot(), if requested, while we're still root and before chdir/privilege-drop, so the
+        // daemon can't escape the jail once it drops to an unprivileged uid/gid.
+        if let Some(path) = &self.chroot
+            && let Err(_err) = chroot(path)
+        {
+            process::exit(ExitCodes::GrandchildChrootFailed.into());
+        }
+
+        // chdir() into the working directory (default "/") to ensure that our process doesn't
+        // keep any directory in use. Failure to do this could make it so that an administrator
+        // couldn't unmount a filesystem, because it was our current directory.
+        if let Err(_err) = set_current_dir(&self.working_directory) {
             process::exit(ExitCodes::GrandchildChdirFailed.into());
         }
 
-        // umask(0) so that we have complete control over the permissions of anything we write. We don't know what umask we may have inherited.
-        // [This step is optional]
-        let _previous_mask = unsafe { libc::umask(0) };
+        // create + lock the pid file, if configured, while we're still privileged enough to write
+        // wherever it lives (e.g. /var/run). The fd is leaked so the lock is held for the
+        // daemon's lifetime; it's only released when the process exits.
+        if let Some(path) = &self.pid_file {
+            match pidfile::create_and_lock(path, process::id() as libc::pid_t) {
+                Ok(file) => std::mem::forget(file),
+                Err(pidfile::LockError::AlreadyRunning) => {
+                    process::exit(ExitCodes::GrandchildAlreadyRunning.into());
+                },
+                Err(pidfile::LockError::Io(_err)) => {
+                    process::exit(ExitCodes::GrandchildPidFileFailed.into());
+                },
+            }
+        }
+
+        // umask() so that we have complete control over the permissions of anything we write.
+        // Defaults to 0, but callers that want e.g. `0o027` instead of world-writable files can
+        // configure it via `DaemonizeOptions::umask`.
+        let previous_umask = unsafe { libc::umask(self.umask) };
+
+        // drop privileges before opening any user-owned files (stdio redirection targets, ...); the
+        // pid file is intentionally created earlier, above, while we're still privileged enough to
+        // write wherever it lives.
+        // order matters: clear/replace supplementary groups, then `setgid`, then `setuid`, since
+        // dropping the uid first would strip the privilege needed to still change gid/supplementary
+        // groups.
+        if self.initgroups || self.group.is_some() || self.user.is_some() {
+            if self.initgroups {
+                let Some(user) = &self.user else {
+                    // `initgroups(3)` needs a login name, which we only have if a user was configured
+                    process::exit(ExitCodes::GrandchildInitgroupsFailed.into());
+                };
+
+                let username = match privilege::resolve_username(user) {
+                    Ok(username) => username,
+                    Err(_err) => process::exit(ExitCodes::GrandchildResolveUserFailed.into()),
+                };
+
+                let initgroups_gid = match &self.group {
+                    Some(group) => match privilege::resolve_gid(group) {
+                        Ok(gid) => gid,
+                        Err(_err) => process::exit(ExitCodes::GrandchildResolveGroupFailed.into()),
+                    },
+                    None => match privilege::resolve_primary_gid(user) {
+                        Ok(gid) => gid,
+                        Err(_err) => process::exit(ExitCodes::GrandchildResolveUserFailed.into()),
+                    },
+                };
+
+                if unsafe { libc::initgroups(username.as_ptr(), initgroups_gid) } != 0 {
+                    process::exit(ExitCodes::GrandchildInitgroupsFailed.into());
+                }
+            } else {
+                // no supplementary-group list requested: drop every inherited one rather than keep
+                // whatever the root parent had (docker, wheel, disk, ...) even after setgid+setuid.
+                if unsafe { libc::setgroups(0, std::ptr::null()) } != 0 {
+                    process::exit(ExitCodes::GrandchildSetgroupsFailed.into());
+                }
+            }
+        }
+
+        if let Some(group) = &self.group {
+            let gid = match privilege::resolve_gid(group) {
+                Ok(gid) => gid,
+                Err(_err) => process::exit(ExitCodes::GrandchildResolveGroupFailed.into()),
+            };
+
+            if unsafe { libc::setgid(gid) } != 0 {
+                process::exit(ExitCodes::GrandchildSetgidFailed.into());
+            }
+        } else if let Some(user) = &self.user {
+            // no explicit group configured: fall back to the user's primary gid, the same base
+            // `initgroups` already uses, so dropping to a user alone doesn't leave the real/effective
+            // gid at whatever the root parent had.
+            let gid = match privilege::resolve_primary_gid(user) {
+                Ok(gid) => gid,
+                Err(_err) => process::exit(ExitCodes::GrandchildResolveUserFailed.into()),
+            };
+
+            if unsafe { libc::setgid(gid) } != 0 {
+                process::exit(ExitCodes::GrandchildSetgidFailed.into());
+            }
+        }
+
+        if let Some(user) = &self.user {
+            let uid = match privilege::resolve_uid(user) {
+                Ok(uid) => uid,
+                Err(_err) => process::exit(ExitCodes::GrandchildResolveUserFailed.into()),
+            };
+
+            if unsafe { libc::setuid(uid) } != 0 {
+                process::exit(ExitCodes::GrandchildSetuidFailed.into());
+            }
+        }
 
         // close() fds 0, 1, and 2. This releases the standard in, out, and error we inherited from our parent process.
         // We have no way of knowing where these fds might have been redirected to.
@@ -242,24 +568,33 @@ impl DaemonizeOptions {
         // The precise handling of these is a matter of taste; if you have a logfile, for example, you might wish to open it as stdout or stderr, and open `/dev/null' as stdin; alternatively, you could open `/dev/console' as stderr and/or stdout, and `/dev/null' as stdin, or any other combination that makes sense for your particular daemon.
 
         // we're doing both the closing and establishing new descriptors with a dup2 call instead of close and re-open (and hoping we get 0, 1 & 2)
-        let fd = match OpenOptions::new().read(true).write(true).open("/dev/null") {
-            Ok(file) => file.into_raw_fd(),
-            Err(_err) => {
-                // couldn't open /dev/null?
-                process::exit(ExitCodes::GrandchildOpenDevNullFailed.into());
-            },
+        let stdin_fd = match redirect::resolve_raw_fd(self.stdin, false) {
+            Ok(fd) => fd,
+            Err(_err) => process::exit(ExitCodes::GrandchildOpenStdinFailed.into()),
+        };
+
+        let stdout_fd = match redirect::resolve_raw_fd(self.stdout, true) {
+            Ok(fd) => fd,
+            Err(_err) => process::exit(ExitCodes::GrandchildOpenStdoutFailed.into()),
+        };
+
+        let stderr_fd = match redirect::resolve_raw_fd(self.stderr, true) {
+            Ok(fd) => fd,
+            Err(_err) => process::exit(ExitCodes::GrandchildOpenStderrFailed.into()),
         };
 
-        let _r = dup2(fd, libc::STDIN_FILENO);
-        let _r = dup2(fd, libc::STDOUT_FILENO);
-        let _r = dup2(fd, libc::STDERR_FILENO);
+        let _r = dup2(stdin_fd, libc::STDIN_FILENO);
+        let _r = dup2(stdout_fd, libc::STDOUT_FILENO);
+        let _r = dup2(stderr_fd, libc::STDERR_FILENO);
 
-        if fd > 2 {
-            // fd is not one of the pre-defined ones, let's close it
-            let _r = close(fd);
+        for fd in [stdin_fd, stdout_fd, stderr_fd] {
+            if fd > 2 {
+                // fd is not one of the pre-defined ones, let's close it
+                let _r = close(fd);
+            }
         }
 
-        Ok(Identity::Daemon)
+        Ok(Identity::Daemon { previous_umask })
     }
 }
 
@@ -274,7 +609,7 @@ mod tests {
     fn test_child_1() {
         let result = match daemonize() {
             Ok(Identity::Original) => Ok(()),
-            Ok(Identity::Daemon) => {
+            Ok(Identity::Daemon { .. }) => {
                 thread::sleep(Duration::from_secs(2));
                 Ok(())
             },
@@ -0,0 +1,98 @@
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::os::unix::io::AsRawFd;
+use std::path::Path;
+
+/// Why [`create_and_lock`] failed.
+#[derive(Debug)]
+pub(crate) enum LockError {
+    /// The lock is already held, i.e. another instance is running.
+    AlreadyRunning,
+    Io(io::Error),
+}
+
+impl From<io::Error> for LockError {
+    fn from(err: io::Error) -> Self {
+        LockError::Io(err)
+    }
+}
+
+/// Opens (creating if needed) the pid file at `path`, takes a non-blocking
+/// exclusive `flock`, truncates it, and writes `pid`.
+///
+/// The caller must keep the returned `File` alive (e.g. leak it) for the
+/// lifetime of the daemon: the lock is released as soon as the fd is closed.
+pub(crate) fn create_and_lock(path: &Path, pid: libc::pid_t) -> Result<File, LockError> {
+    let file = OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .read(true)
+        .write(true)
+        .open(path)?;
+
+    let rc = unsafe { libc::flock(file.as_raw_fd(), libc::LOCK_EX | libc::LOCK_NB) };
+
+    if rc != 0 {
+        let err = io::Error::last_os_error();
+
+        return Err(if err.kind() == io::ErrorKind::WouldBlock {
+            LockError::AlreadyRunning
+        } else {
+            LockError::Io(err)
+        });
+    }
+
+    write_pid(&file, pid)?;
+
+    Ok(file)
+}
+
+fn write_pid(mut file: &File, pid: libc::pid_t) -> io::Result<()> {
+    file.set_len(0)?;
+    file.seek(SeekFrom::Start(0))?;
+    writeln!(file, "{pid}")?;
+    file.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+
+    use super::{LockError, create_and_lock};
+
+    #[test]
+    fn writes_pid_and_holds_lock() {
+        let path = std::env::temp_dir().join(format!("fork-rs-pidfile-test-{}.pid", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let file = create_and_lock(&path, 1234).unwrap();
+
+        let mut contents = String::new();
+        fs::File::open(&path).unwrap().read_to_string(&mut contents).unwrap();
+        assert_eq!(contents, "1234\n");
+
+        drop(file);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn second_lock_on_same_file_fails_as_already_running() {
+        let path =
+            std::env::temp_dir().join(format!("fork-rs-pidfile-test-contention-{}.pid", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let first = create_and_lock(&path, 1234).unwrap();
+
+        match create_and_lock(&path, 5678) {
+            Err(LockError::AlreadyRunning) => {},
+            Err(LockError::Io(err)) => panic!("expected AlreadyRunning, got Io({err})"),
+            Ok(_) => panic!("expected AlreadyRunning, got Ok"),
+        }
+
+        drop(first);
+        fs::remove_file(&path).unwrap();
+    }
+}
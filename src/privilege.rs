@@ -0,0 +1,200 @@
+use std::ffi::{CStr, CString};
+use std::io;
+use std::mem::MaybeUninit;
+use std::ptr;
+
+/// A user to switch to when dropping privileges.
+///
+/// Either a numeric id, or a name that is resolved via `getpwnam_r` once the
+/// grandchild is ready to drop privileges.
+#[derive(Debug, Clone)]
+pub enum User {
+    Id(libc::uid_t),
+    Name(String),
+}
+
+impl From<libc::uid_t> for User {
+    fn from(uid: libc::uid_t) -> Self {
+        User::Id(uid)
+    }
+}
+
+impl From<&str> for User {
+    fn from(name: &str) -> Self {
+        User::Name(name.to_owned())
+    }
+}
+
+impl From<String> for User {
+    fn from(name: String) -> Self {
+        User::Name(name)
+    }
+}
+
+/// A group to switch to when dropping privileges.
+///
+/// Either a numeric id, or a name that is resolved via `getgrnam_r` once the
+/// grandchild is ready to drop privileges.
+#[derive(Debug, Clone)]
+pub enum Group {
+    Id(libc::gid_t),
+    Name(String),
+}
+
+impl From<libc::gid_t> for Group {
+    fn from(gid: libc::gid_t) -> Self {
+        Group::Id(gid)
+    }
+}
+
+impl From<&str> for Group {
+    fn from(name: &str) -> Self {
+        Group::Name(name.to_owned())
+    }
+}
+
+impl From<String> for Group {
+    fn from(name: String) -> Self {
+        Group::Name(name)
+    }
+}
+
+// `getpwnam_r`/`getgrnam_r` want a caller-owned scratch buffer; NSS-backed
+// lookups (LDAP, etc) can need more than the handful of bytes a flat-file
+// lookup does, so we allocate generously rather than looping on `ERANGE`.
+const LOOKUP_BUFFER_SIZE: usize = 16 * 1024;
+
+// `f` runs while `buf` is still alive, since the `passwd`/`group` struct it's handed back
+// borrows string fields (`pw_name`, ...) that point straight into that buffer; returning
+// the struct by value instead would leave those pointers dangling the moment `buf` drops.
+
+fn with_pwnam<T>(name: &str, f: impl FnOnce(&libc::passwd) -> T) -> io::Result<T> {
+    let c_name = CString::new(name).map_err(io::Error::other)?;
+
+    let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+    let mut buf = vec![0_u8; LOOKUP_BUFFER_SIZE];
+    let mut result: *mut libc::passwd = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getpwnam_r(
+            c_name.as_ptr(),
+            passwd.as_mut_ptr(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            &raw mut result,
+        )
+    };
+
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+
+    if result.is_null() {
+        return Err(io::Error::other(format!("No such user: {name}")));
+    }
+
+    Ok(f(unsafe { &*result }))
+}
+
+fn with_pwuid<T>(uid: libc::uid_t, f: impl FnOnce(&libc::passwd) -> T) -> io::Result<T> {
+    let mut passwd = MaybeUninit::<libc::passwd>::uninit();
+    let mut buf = vec![0_u8; LOOKUP_BUFFER_SIZE];
+    let mut result: *mut libc::passwd = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getpwuid_r(
+            uid,
+            passwd.as_mut_ptr(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            &raw mut result,
+        )
+    };
+
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+
+    if result.is_null() {
+        return Err(io::Error::other(format!("No such uid: {uid}")));
+    }
+
+    Ok(f(unsafe { &*result }))
+}
+
+fn with_grnam<T>(name: &str, f: impl FnOnce(&libc::group) -> T) -> io::Result<T> {
+    let c_name = CString::new(name).map_err(io::Error::other)?;
+
+    let mut group = MaybeUninit::<libc::group>::uninit();
+    let mut buf = vec![0_u8; LOOKUP_BUFFER_SIZE];
+    let mut result: *mut libc::group = ptr::null_mut();
+
+    let rc = unsafe {
+        libc::getgrnam_r(
+            c_name.as_ptr(),
+            group.as_mut_ptr(),
+            buf.as_mut_ptr().cast(),
+            buf.len(),
+            &raw mut result,
+        )
+    };
+
+    if rc != 0 {
+        return Err(io::Error::from_raw_os_error(rc));
+    }
+
+    if result.is_null() {
+        return Err(io::Error::other(format!("No such group: {name}")));
+    }
+
+    Ok(f(unsafe { &*result }))
+}
+
+/// Resolves a [`User`] to a uid, looking it up by name if necessary.
+pub(crate) fn resolve_uid(user: &User) -> io::Result<libc::uid_t> {
+    match user {
+        User::Id(uid) => Ok(*uid),
+        User::Name(name) => with_pwnam(name, |passwd| passwd.pw_uid),
+    }
+}
+
+/// Resolves a [`Group`] to a gid, looking it up by name if necessary.
+pub(crate) fn resolve_gid(group: &Group) -> io::Result<libc::gid_t> {
+    match group {
+        Group::Id(gid) => Ok(*gid),
+        Group::Name(name) => with_grnam(name, |group| group.gr_gid),
+    }
+}
+
+/// Resolves a [`User`]'s primary gid, used as the base group for `initgroups(3)`
+/// when no explicit [`Group`] was configured to drop to.
+pub(crate) fn resolve_primary_gid(user: &User) -> io::Result<libc::gid_t> {
+    match user {
+        User::Id(uid) => with_pwuid(*uid, |passwd| passwd.pw_gid),
+        User::Name(name) => with_pwnam(name, |passwd| passwd.pw_gid),
+    }
+}
+
+/// Resolves a [`User`] to the login name `initgroups(3)` wants, reverse
+/// looking it up by uid if necessary.
+pub(crate) fn resolve_username(user: &User) -> io::Result<CString> {
+    match user {
+        User::Name(name) => CString::new(name.as_str()).map_err(io::Error::other),
+        User::Id(uid) => with_pwuid(*uid, |passwd| unsafe { CStr::from_ptr(passwd.pw_name) }.to_owned()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Group, User, resolve_gid, resolve_uid};
+
+    #[test]
+    fn resolve_uid_passes_through_numeric_id() {
+        assert_eq!(resolve_uid(&User::Id(1000)).unwrap(), 1000);
+    }
+
+    #[test]
+    fn resolve_gid_passes_through_numeric_id() {
+        assert_eq!(resolve_gid(&Group::Id(1000)).unwrap(), 1000);
+    }
+}
@@ -0,0 +1,107 @@
+use std::fs::OpenOptions;
+use std::io;
+use std::os::fd::{IntoRawFd, OwnedFd, RawFd};
+use std::path::PathBuf;
+
+/// A target to redirect one of the daemon's standard streams to.
+#[derive(Default)]
+pub enum Redirect {
+    /// `/dev/null` (the default).
+    #[default]
+    Null,
+    /// An already-open file descriptor, `dup2`'d onto the target stream.
+    Fd(OwnedFd),
+    /// A path, opened fresh: read-only for stdin, `O_CREAT|O_APPEND|O_WRONLY` for stdout/stderr.
+    Path(PathBuf),
+}
+
+impl From<OwnedFd> for Redirect {
+    fn from(fd: OwnedFd) -> Self {
+        Redirect::Fd(fd)
+    }
+}
+
+impl From<PathBuf> for Redirect {
+    fn from(path: PathBuf) -> Self {
+        Redirect::Path(path)
+    }
+}
+
+impl From<&str> for Redirect {
+    fn from(path: &str) -> Self {
+        Redirect::Path(PathBuf::from(path))
+    }
+}
+
+/// Resolves a [`Redirect`] to a raw fd ready to be `dup2`'d onto a standard
+/// stream. `append` picks the open mode for [`Redirect::Path`]: `false` opens
+/// read-only (stdin), `true` opens `O_CREAT|O_APPEND|O_WRONLY` (stdout/stderr).
+pub(crate) fn resolve_raw_fd(redirect: Redirect, append: bool) -> io::Result<RawFd> {
+    match redirect {
+        Redirect::Null => Ok(OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/null")?
+            .into_raw_fd()),
+        Redirect::Fd(fd) => Ok(fd.into_raw_fd()),
+        Redirect::Path(path) => {
+            let mut options = OpenOptions::new();
+
+            if append {
+                options.create(true).append(true);
+            } else {
+                options.read(true);
+            }
+
+            Ok(options.open(path)?.into_raw_fd())
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Read;
+    use std::os::fd::FromRawFd;
+
+    use super::{Redirect, resolve_raw_fd};
+
+    #[test]
+    fn null_opens_dev_null() {
+        let fd = resolve_raw_fd(Redirect::Null, false).unwrap();
+        let mut file = unsafe { fs::File::from_raw_fd(fd) };
+
+        let mut buf = [0_u8; 1];
+        assert_eq!(file.read(&mut buf).unwrap(), 0);
+    }
+
+    #[test]
+    fn path_read_opens_existing_file() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("fork-rs-redirect-test-{}.txt", std::process::id()));
+        fs::write(&path, b"hello").unwrap();
+
+        let fd = resolve_raw_fd(Redirect::Path(path.clone()), false).unwrap();
+        let mut file = unsafe { fs::File::from_raw_fd(fd) };
+
+        let mut buf = String::new();
+        file.read_to_string(&mut buf).unwrap();
+        assert_eq!(buf, "hello");
+
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn path_append_creates_and_appends() {
+        let dir = std::env::temp_dir();
+        let path = dir.join(format!("fork-rs-redirect-test-append-{}.txt", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let fd = resolve_raw_fd(Redirect::Path(path.clone()), true).unwrap();
+        drop(unsafe { fs::File::from_raw_fd(fd) });
+
+        assert!(path.exists());
+
+        fs::remove_file(&path).unwrap();
+    }
+}
@@ -0,0 +1,197 @@
+use std::process;
+use std::sync::atomic::{AtomicBool, AtomicI32, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::{ExitCodes, Fork, fork};
+
+/// When to respawn the worker after it exits.
+#[derive(Debug, Clone, Copy)]
+pub enum RestartPolicy {
+    /// Never respawn; the supervisor exits once the worker does.
+    Never,
+    /// Always respawn, regardless of how the worker exited.
+    Always,
+    /// Only respawn if the worker exited with a nonzero status or was killed by a signal.
+    OnFailure,
+}
+
+/// Supervisor configuration: a [`RestartPolicy`] plus the exponential backoff
+/// applied between respawns.
+pub struct SuperviseOptions {
+    policy: RestartPolicy,
+    initial_backoff: Duration,
+    max_backoff: Duration,
+    reset_after: Duration,
+}
+
+impl SuperviseOptions {
+    #[must_use]
+    pub fn new(policy: RestartPolicy) -> Self {
+        Self {
+            policy,
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+            reset_after: Duration::from_secs(60),
+        }
+    }
+
+    /// Caps the exponential backoff applied between respawns. Default 30s.
+    #[must_use]
+    pub fn max_backoff(mut self, max_backoff: Duration) -> Self {
+        self.max_backoff = max_backoff;
+
+        self
+    }
+
+    /// Once a worker has stayed alive this long, the backoff resets back to
+    /// the initial delay for the next respawn. Default 60s.
+    #[must_use]
+    pub fn reset_after(mut self, reset_after: Duration) -> Self {
+        self.reset_after = reset_after;
+
+        self
+    }
+}
+
+// Shared between the `SIGTERM` handler below and the supervisor loop (the loop
+// writes `WORKER_PID` and reads `TERMINATING`, the handler the reverse); both sides only ever need the latest value, so `SeqCst` is simplicity over
+// performance here, not a correctness requirement.
+static WORKER_PID: AtomicI32 = AtomicI32::new(0);
+static TERMINATING: AtomicBool = AtomicBool::new(false);
+
+// Forwards `SIGTERM` to the current worker and marks the supervisor as
+// shutting down. `kill` and atomic stores are async-signal-safe, so this is
+// safe to run directly in the handler.
+extern "C" fn handle_sigterm(_signal: libc::c_int) {
+    let worker_pid = WORKER_PID.load(Ordering::SeqCst);
+
+    if worker_pid > 0 {
+        unsafe {
+            libc::kill(worker_pid, libc::SIGTERM);
+        }
+    }
+
+    TERMINATING.store(true, Ordering::SeqCst);
+}
+
+enum WorkerExit {
+    Exited { code: i32 },
+    Signaled,
+}
+
+fn waitpid(pid: i32) -> Result<WorkerExit, std::io::Error> {
+    let mut status = 0;
+
+    cvt::cvt_r(|| unsafe { libc::waitpid(pid, &raw mut status, 0) })?;
+
+    Ok(if libc::WIFSIGNALED(status) {
+        WorkerExit::Signaled
+    } else {
+        WorkerExit::Exited {
+            code: libc::WEXITSTATUS(status),
+        }
+    })
+}
+
+fn should_restart(policy: RestartPolicy, exit: &WorkerExit) -> bool {
+    match policy {
+        RestartPolicy::Never => false,
+        RestartPolicy::Always => true,
+        RestartPolicy::OnFailure => match exit {
+            WorkerExit::Exited { code } => *code != 0,
+            WorkerExit::Signaled => true,
+        },
+    }
+}
+
+/// Runs the supervisor loop: forks a worker, waits for it, and respawns it
+/// according to `options.policy` with exponential backoff, until the worker
+/// exits in a way the policy doesn't want restarted, or `SIGTERM` is
+/// delivered to the supervisor.
+///
+/// Returns `Ok(())` in the freshly-forked worker so the caller can continue
+/// daemonizing. The supervisor process itself never returns; it exits via
+/// `process::exit` instead.
+pub(crate) fn supervise(options: &SuperviseOptions) -> Result<(), std::io::Error> {
+    // installed once, inherited across every respawned worker's supervisor loop iteration
+    unsafe {
+        libc::signal(libc::SIGTERM, handle_sigterm as *const () as libc::sighandler_t);
+    }
+
+    let mut backoff = options.initial_backoff;
+
+    loop {
+        match fork()? {
+            Fork::Child => {
+                // `SIGTERM` is the supervisor's signal, not the worker's: reset it to the
+                // default disposition so the worker dies like any other process instead of
+                // silently running the supervisor's handler (which only forwards to
+                // `WORKER_PID`, stale/zero here) and never actually terminating.
+                unsafe {
+                    libc::signal(libc::SIGTERM, libc::SIG_DFL);
+                }
+
+                return Ok(());
+            },
+            Fork::Parent { child } => {
+                WORKER_PID.store(child, Ordering::SeqCst);
+
+                let started_at = Instant::now();
+
+                let exit = waitpid(child)?;
+
+                // sampled before the sleep below: it's the worker's uptime we want to compare
+                // against `reset_after`, not uptime-plus-the-backoff-delay-we're-about-to-sleep.
+                let uptime = started_at.elapsed();
+
+                WORKER_PID.store(0, Ordering::SeqCst);
+
+                if TERMINATING.load(Ordering::SeqCst) || !should_restart(options.policy, &exit) {
+                    process::exit(ExitCodes::Ok.into());
+                }
+
+                if uptime >= options.reset_after {
+                    backoff = options.initial_backoff;
+                }
+
+                thread::sleep(backoff);
+
+                // a `SIGTERM` delivered while no worker was alive to forward it to is only
+                // recorded in `TERMINATING`; check it again now that we've woken up, before
+                // spawning another worker.
+                if TERMINATING.load(Ordering::SeqCst) {
+                    process::exit(ExitCodes::Ok.into());
+                }
+
+                backoff = (backoff * 2).min(options.max_backoff);
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{RestartPolicy, WorkerExit, should_restart};
+
+    #[test]
+    fn never_does_not_restart() {
+        assert!(!should_restart(RestartPolicy::Never, &WorkerExit::Exited { code: 0 }));
+        assert!(!should_restart(RestartPolicy::Never, &WorkerExit::Exited { code: 1 }));
+        assert!(!should_restart(RestartPolicy::Never, &WorkerExit::Signaled));
+    }
+
+    #[test]
+    fn always_restarts() {
+        assert!(should_restart(RestartPolicy::Always, &WorkerExit::Exited { code: 0 }));
+        assert!(should_restart(RestartPolicy::Always, &WorkerExit::Exited { code: 1 }));
+        assert!(should_restart(RestartPolicy::Always, &WorkerExit::Signaled));
+    }
+
+    #[test]
+    fn on_failure_restarts_only_on_nonzero_or_signal() {
+        assert!(!should_restart(RestartPolicy::OnFailure, &WorkerExit::Exited { code: 0 }));
+        assert!(should_restart(RestartPolicy::OnFailure, &WorkerExit::Exited { code: 1 }));
+        assert!(should_restart(RestartPolicy::OnFailure, &WorkerExit::Signaled));
+    }
+}